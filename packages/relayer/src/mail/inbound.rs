@@ -0,0 +1,224 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_native_tls::TlsStream;
+use futures::TryStreamExt;
+use relayer_utils::ParsedEmail;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    time::sleep,
+};
+use uuid::Uuid;
+
+use crate::{
+    model::{get_request, RequestModel},
+    RelayerState,
+};
+
+use super::{check_is_valid_request, handle_email, handle_email_event};
+
+/// Which flavor of transport-layer security to use when connecting to the
+/// IMAP server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImapTlsMode {
+    /// Wrap the connection in TLS from the first byte, e.g. on port 993.
+    Implicit,
+    /// Connect in plaintext and issue `STARTTLS` before authenticating.
+    StartTls,
+}
+
+/// Configuration for the inbound IMAP poller.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImapConfig {
+    pub host: String,
+    pub port: u16,
+    pub tls: ImapTlsMode,
+    pub username: String,
+    pub password: String,
+    #[serde(default = "default_folder")]
+    pub folder: String,
+    #[serde(default = "default_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+}
+
+fn default_folder() -> String {
+    "INBOX".to_string()
+}
+
+fn default_poll_interval_secs() -> u64 {
+    30
+}
+
+type ImapSession = async_imap::Session<TlsStream<TcpStream>>;
+
+/// Runs the inbound poller until cancelled, reconnecting and logging any
+/// per-cycle failure rather than giving up on the mailbox entirely.
+///
+/// # Arguments
+///
+/// * `config` - The IMAP connection and polling configuration.
+/// * `relayer_state` - Shared relayer state, used to reach the database and
+///   to re-enter `handle_email`/`handle_email_event` for each reply found.
+pub async fn run_inbound_poller(config: ImapConfig, relayer_state: RelayerState) -> Result<()> {
+    loop {
+        if let Err(err) = poll_once(&config, &relayer_state).await {
+            tracing::error!("IMAP poll of {} failed: {err:#}", config.folder);
+        }
+
+        sleep(Duration::from_secs(config.poll_interval_secs)).await;
+    }
+}
+
+/// Connects, fetches every unseen message in `config.folder`, routes each
+/// one through `handle_email`, and marks it seen.
+async fn poll_once(config: &ImapConfig, relayer_state: &RelayerState) -> Result<()> {
+    let mut session = connect(config).await?;
+    session.select(&config.folder).await?;
+
+    let uids = session.uid_search("UNSEEN").await?;
+    for uid in uids {
+        if let Err(err) = process_message(&mut session, uid, relayer_state).await {
+            tracing::error!("failed to process IMAP message uid={uid}: {err:#}");
+        }
+    }
+
+    session.logout().await?;
+    Ok(())
+}
+
+async fn connect(config: &ImapConfig) -> Result<ImapSession> {
+    let tcp_stream = TcpStream::connect((config.host.as_str(), config.port)).await?;
+
+    let tls_stream = match config.tls {
+        ImapTlsMode::Implicit => async_native_tls::connect(&config.host, tcp_stream).await?,
+        ImapTlsMode::StartTls => {
+            let tcp_stream = starttls_upgrade_plaintext(tcp_stream).await?;
+            async_native_tls::connect(&config.host, tcp_stream).await?
+        }
+    };
+
+    let client = async_imap::Client::new(tls_stream);
+    client
+        .login(&config.username, &config.password)
+        .await
+        .map_err(|(err, _client)| anyhow!("IMAP login failed: {err}"))
+}
+
+/// Negotiates `STARTTLS` on a freshly-connected, still-plaintext IMAP
+/// socket: reads the server greeting, issues `STARTTLS`, and confirms the
+/// server's `OK` before handing the stream back for the actual TLS upgrade.
+/// Without this, `ImapTlsMode::StartTls` never tells the server to expect
+/// TLS at all, so any real STARTTLS-only server simply hangs up the moment
+/// the TLS handshake arrives in the clear.
+async fn starttls_upgrade_plaintext(stream: TcpStream) -> Result<TcpStream> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+
+    reader.read_line(&mut line).await?;
+    if !line.starts_with("* OK") {
+        return Err(anyhow!("unexpected IMAP greeting: {line:?}"));
+    }
+
+    let mut stream = reader.into_inner();
+    stream.write_all(b"a1 STARTTLS\r\n").await?;
+
+    let mut reader = BufReader::new(stream);
+    line.clear();
+    reader.read_line(&mut line).await?;
+    if !line.starts_with("a1 OK") {
+        return Err(anyhow!("IMAP server rejected STARTTLS: {line:?}"));
+    }
+
+    Ok(reader.into_inner())
+}
+
+/// Downloads one message, hands it to `handle_email` if it reconciles to a
+/// request expecting a reply, and marks it seen either way so it is not
+/// reprocessed on the next poll.
+async fn process_message(
+    session: &mut ImapSession,
+    uid: u32,
+    relayer_state: &RelayerState,
+) -> Result<()> {
+    let fetches: Vec<_> = session
+        .uid_fetch(uid.to_string(), "RFC822")
+        .await?
+        .try_collect()
+        .await?;
+
+    if let Some(raw_email) = fetches.into_iter().find_map(|fetch| {
+        fetch
+            .body()
+            .map(|body| String::from_utf8_lossy(body).into_owned())
+    }) {
+        route_reply(raw_email, relayer_state).await?;
+    }
+
+    session
+        .uid_store(uid.to_string(), "+FLAGS.SILENT (\\Seen)")
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    Ok(())
+}
+
+/// Validates an inbound message and, if it is a genuine reply to a request
+/// awaiting one, re-enters the same `handle_email` / `handle_email_event`
+/// path used by any other reply source.
+async fn route_reply(raw_email: String, relayer_state: &RelayerState) -> Result<()> {
+    let parsed_email = ParsedEmail::new_from_raw_email(&raw_email).await?;
+
+    if !check_is_valid_request(&parsed_email, &relayer_state.db).await? {
+        return Ok(());
+    }
+
+    let Some(request) = resolve_originating_request(&parsed_email, &relayer_state.db).await? else {
+        return Ok(());
+    };
+
+    let event = handle_email(raw_email, request, relayer_state.clone()).await?;
+    handle_email_event(event, relayer_state.clone()).await
+}
+
+/// Matches `email`'s `In-Reply-To`/`References` headers against the
+/// `expected_reply` table (the same table `queue.rs` writes to via
+/// `insert_expected_reply` on confirmed delivery) to find the request this
+/// message is replying to.
+async fn resolve_originating_request(
+    email: &ParsedEmail,
+    pool: &PgPool,
+) -> Result<Option<RequestModel>> {
+    for header_name in ["In-Reply-To", "References"] {
+        let Some(values) = email.headers.get_header(header_name) else {
+            continue;
+        };
+
+        for message_id in values {
+            let Some(request_id) = lookup_expected_reply(pool, &message_id).await? else {
+                continue;
+            };
+
+            return Ok(Some(get_request(pool, request_id).await?));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Looks up the request id an outbound message-id was registered against.
+async fn lookup_expected_reply(pool: &PgPool, message_id: &str) -> Result<Option<Uuid>> {
+    let row: Option<(Option<String>,)> =
+        sqlx::query_as("SELECT request_id FROM expected_reply WHERE message_id = $1")
+            .bind(message_id)
+            .fetch_optional(pool)
+            .await?;
+
+    Ok(row
+        .and_then(|(request_id,)| request_id)
+        .and_then(|id| id.parse::<Uuid>().ok()))
+}
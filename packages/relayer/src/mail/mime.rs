@@ -0,0 +1,124 @@
+use anyhow::Result;
+use lettre::message::{
+    header::ContentType, Attachment, Message, MessageBuilder, MultiPart, SinglePart,
+};
+use uuid::Uuid;
+
+use super::{EmailAttachment, EmailMessage};
+
+/// Assembles `email` into a MIME `Message`: a `multipart/alternative`
+/// plain/HTML body, wrapped in `multipart/related` when there are inline
+/// attachments so they can be referenced from the HTML part as `cid:`
+/// images. Returns the message together with the Message-ID it was given,
+/// so the caller can keep registering it against the expected-reply table.
+///
+/// Non-ASCII subjects and display names are RFC 2047 encoded-words by
+/// virtue of going through `lettre`'s `Mailbox`/header types below, rather
+/// than being written out as raw UTF-8 -- that's what used to garble
+/// non-Latin commands in transit.
+///
+/// `from` is the relayer's own configured sender mailbox (`SmtpConfig::from`).
+/// `email.reference`/`email.reply_to` hold the original message's Message-ID
+/// for threading, never a mailbox, so they must never end up in `From`.
+pub fn build_message(email: &EmailMessage, from: &str) -> Result<(Message, String)> {
+    let message_id = format!("<{}@{}>", Uuid::new_v4(), "email-tx-builder");
+
+    let mut builder: MessageBuilder = Message::builder()
+        .from(from.parse()?)
+        .to(email.to.parse()?)
+        .message_id(Some(message_id.clone()))
+        .subject(email.subject.as_str());
+
+    if let Some(reference) = &email.reference {
+        builder = builder.in_reply_to(reference.clone());
+    }
+
+    let body = alternative_part(email);
+    let attachments = email.body_attachments.as_deref().unwrap_or(&[]);
+
+    let multipart = if attachments.is_empty() {
+        body
+    } else {
+        attachments.iter().fold(
+            MultiPart::related().multipart(body),
+            |related, attachment| related.singlepart(inline_attachment_part(attachment)),
+        )
+    };
+
+    Ok((builder.multipart(multipart)?, message_id))
+}
+
+/// The plain/HTML alternative that makes up the text of the email.
+fn alternative_part(email: &EmailMessage) -> MultiPart {
+    MultiPart::alternative()
+        .singlepart(
+            SinglePart::builder()
+                .header(ContentType::TEXT_PLAIN)
+                .body(email.body_plain.clone()),
+        )
+        .singlepart(
+            SinglePart::builder()
+                .header(ContentType::TEXT_HTML)
+                .body(email.body_html.clone()),
+        )
+}
+
+/// Builds one inline attachment part, referenceable from the HTML body as
+/// `cid:<inline_id>`, with the attachment's declared content type.
+fn inline_attachment_part(attachment: &EmailAttachment) -> SinglePart {
+    let content_type = attachment
+        .content_type
+        .parse()
+        .unwrap_or(ContentType::parse("application/octet-stream").unwrap());
+
+    Attachment::new_inline(attachment.inline_id.clone())
+        .body(attachment.contents.clone(), content_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::build_message;
+    use crate::mail::EmailMessage;
+
+    fn email(reference: Option<&str>, reply_to: Option<&str>) -> EmailMessage {
+        EmailMessage {
+            to: "user@example.com".to_string(),
+            subject: "Re: your request".to_string(),
+            reference: reference.map(str::to_string),
+            reply_to: reply_to.map(str::to_string),
+            body_plain: "plain body".to_string(),
+            body_html: "<p>html body</p>".to_string(),
+            body_attachments: None,
+        }
+    }
+
+    #[test]
+    fn from_is_the_configured_sender_never_the_recipient_or_a_message_id() {
+        let message = email(Some("<orig@theirdomain>"), Some("<orig@theirdomain>"));
+        let (built, _) = build_message(&message, "ZK Email <noreply@prove.email>").unwrap();
+        let formatted = String::from_utf8_lossy(&built.formatted()).into_owned();
+
+        assert!(formatted.contains("From: \"ZK Email\" <noreply@prove.email>"));
+        assert!(formatted.contains("To: user@example.com"));
+        assert!(!formatted.contains("From: <orig@theirdomain>"));
+        assert!(!formatted.contains("From: user@example.com"));
+    }
+
+    #[test]
+    fn in_reply_to_is_set_from_the_reference_when_present() {
+        let message = email(Some("<orig@theirdomain>"), None);
+        let (built, _) = build_message(&message, "noreply@prove.email").unwrap();
+        let formatted = String::from_utf8_lossy(&built.formatted()).into_owned();
+
+        assert!(formatted.contains("In-Reply-To: <orig@theirdomain>"));
+    }
+
+    #[test]
+    fn in_reply_to_is_absent_for_a_fresh_command_email() {
+        let message = email(None, None);
+        let (built, _) = build_message(&message, "noreply@prove.email").unwrap();
+        let formatted = String::from_utf8_lossy(&built.formatted()).into_owned();
+
+        assert!(!formatted.contains("In-Reply-To"));
+    }
+}
@@ -1,13 +1,9 @@
-use std::path::PathBuf;
-
 use anyhow::Result;
 use ethers::types::U256;
-use handlebars::Handlebars;
 use relayer_utils::ParsedEmail;
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
 use sqlx::PgPool;
-use tokio::fs::read_to_string;
+use tokio::task::JoinHandle;
 use uuid::Uuid;
 
 use crate::{
@@ -15,11 +11,56 @@ use crate::{
     chain::ChainClient,
     command::get_encoded_command_params,
     dkim::check_and_update_dkim,
-    model::{insert_expected_reply, is_valid_reply, update_request, RequestModel, RequestStatus},
+    model::{is_valid_reply, RequestModel},
     prove::generate_email_proof,
     RelayerState,
 };
 
+mod inbound;
+mod mime;
+mod queue;
+mod templates;
+mod transport;
+
+pub use inbound::{run_inbound_poller, ImapConfig, ImapTlsMode};
+pub use queue::run_outbound_worker;
+pub use templates::TemplateRegistry;
+pub use transport::{SmtpAuthMechanism, SmtpConfig, SmtpTlsMode, Transport};
+
+/// The locale to fall back to when a recipient has no locale on file, or the
+/// locale they are on file for has no translated template.
+const DEFAULT_LOCALE: &str = "en";
+
+/// Spawns the background workers this module depends on to actually move
+/// mail: the outbound queue's retrying delivery loop, and -- when IMAP
+/// polling is configured -- the inbound reply poller. Callers (the
+/// relayer's startup code) are responsible for holding onto the returned
+/// handles and awaiting or aborting them on shutdown; neither loop is
+/// reachable without a call to this function somewhere on the startup path.
+pub fn spawn_workers(
+    relayer_state: RelayerState,
+    imap_config: Option<ImapConfig>,
+) -> (JoinHandle<()>, Option<JoinHandle<()>>) {
+    let outbound = {
+        let relayer_state = relayer_state.clone();
+        tokio::spawn(async move {
+            if let Err(err) = run_outbound_worker(relayer_state).await {
+                tracing::error!("outbound email worker exited: {err:#}");
+            }
+        })
+    };
+
+    let inbound = imap_config.map(|config| {
+        tokio::spawn(async move {
+            if let Err(err) = run_inbound_poller(config, relayer_state).await {
+                tracing::error!("inbound email poller exited: {err:#}");
+            }
+        })
+    });
+
+    (outbound, inbound)
+}
+
 /// Represents an email message to be sent.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmailMessage {
@@ -50,24 +91,29 @@ pub enum EmailEvent {
         account_code: Option<String>,
         subject: String,
         body: String,
+        /// The recipient's preferred locale (e.g. `"ja"`), if known.
+        locale: Option<String>,
     },
     Ack {
         email_addr: String,
         command: String,
         original_message_id: Option<String>,
         original_subject: String,
+        locale: Option<String>,
     },
     Completion {
         email_addr: String,
         request_id: Uuid,
         original_subject: String,
         original_message_id: Option<String>,
+        locale: Option<String>,
     },
     Error {
         email_addr: String,
         error: String,
         original_subject: String,
         original_message_id: Option<String>,
+        locale: Option<String>,
     },
 }
 
@@ -89,6 +135,7 @@ pub async fn handle_email_event(event: EmailEvent, relayer_state: RelayerState)
             account_code,
             subject,
             body,
+            locale,
         } => {
             // Prepare the command with the account code if it exists
             let command = if let Some(code) = account_code {
@@ -110,8 +157,12 @@ pub async fn handle_email_event(event: EmailEvent, relayer_state: RelayerState)
                 "requestId": request_id,
                 "command": command,
             });
-            let body_html =
-                render_html("command_template.html", render_data, relayer_state.clone()).await?;
+            let body_html = render_html(
+                "command_template",
+                locale.as_deref(),
+                render_data,
+                relayer_state.clone(),
+            )?;
 
             // Create and send the email
             let email = EmailMessage {
@@ -124,20 +175,22 @@ pub async fn handle_email_event(event: EmailEvent, relayer_state: RelayerState)
                 body_attachments: None,
             };
 
+            // `run_outbound_worker` advances the request to `EmailSent` once
+            // this email is actually confirmed delivered, not the instant
+            // it's queued here.
             send_email(
                 email,
                 Some(ExpectsReply::new(request_id)),
                 relayer_state.clone(),
             )
             .await?;
-
-            update_request(&relayer_state.db, request_id, RequestStatus::EmailSent).await?;
         }
         EmailEvent::Completion {
             email_addr,
             request_id,
             original_subject,
             original_message_id,
+            locale,
         } => {
             let subject = format!("Re: {}", original_subject);
             let body_plain = format!("Your request ID is #{} is now complete.", request_id);
@@ -147,11 +200,11 @@ pub async fn handle_email_event(event: EmailEvent, relayer_state: RelayerState)
                 "requestId": request_id,
             });
             let body_html = render_html(
-                "completion_template.html",
+                "completion_template",
+                locale.as_deref(),
                 render_data,
                 relayer_state.clone(),
-            )
-            .await?;
+            )?;
 
             // Create and send the email
             let email = EmailMessage {
@@ -171,6 +224,7 @@ pub async fn handle_email_event(event: EmailEvent, relayer_state: RelayerState)
             command,
             original_message_id,
             original_subject,
+            locale,
         } => {
             let body_plain = format!(
                 "Hi {}!\nYour email with the command {} is received.",
@@ -179,11 +233,11 @@ pub async fn handle_email_event(event: EmailEvent, relayer_state: RelayerState)
             // Prepare data for HTML rendering
             let render_data = serde_json::json!({"request": command});
             let body_html = render_html(
-                "acknowledgement_template.html",
+                "acknowledgement_template",
+                locale.as_deref(),
                 render_data,
                 relayer_state.clone(),
-            )
-            .await?;
+            )?;
             let subject = format!("Re: {}", original_subject);
             // Create and send the email
             let email = EmailMessage {
@@ -202,6 +256,7 @@ pub async fn handle_email_event(event: EmailEvent, relayer_state: RelayerState)
             error,
             original_subject,
             original_message_id,
+            locale,
         } => {
             let subject = format!("Re: {}", original_subject);
 
@@ -216,8 +271,12 @@ pub async fn handle_email_event(event: EmailEvent, relayer_state: RelayerState)
                 "error": error,
                 "userEmailAddr": email_addr,
             });
-            let body_html =
-                render_html("error_template.html", render_data, relayer_state.clone()).await?;
+            let body_html = render_html(
+                "error_template",
+                locale.as_deref(),
+                render_data,
+                relayer_state.clone(),
+            )?;
 
             // Create and send the email
             let email = EmailMessage {
@@ -237,38 +296,37 @@ pub async fn handle_email_event(event: EmailEvent, relayer_state: RelayerState)
     Ok(())
 }
 
-/// Renders an HTML template with the given data.
+/// Renders `template_base` (e.g. `"command_template"`) against the
+/// long-lived template registry in `RelayerState`, resolving the
+/// recipient's `locale` to a translated variant when one is registered and
+/// falling back to the default template otherwise.
 ///
 /// # Arguments
 ///
-/// * `template_name` - The name of the template file.
+/// * `template_base` - The template's file stem, without locale or extension.
+/// * `locale` - The recipient's preferred locale, if known.
 /// * `render_data` - The data to be used in rendering the template.
 ///
 /// # Returns
 ///
 /// A `Result` containing the rendered HTML string or an `Error`.
-async fn render_html(
-    template_name: &str,
-    render_data: Value,
+fn render_html(
+    template_base: &str,
+    locale: Option<&str>,
+    render_data: serde_json::Value,
     relayer_state: RelayerState,
 ) -> Result<String> {
-    // Construct the full path to the email template
-    let email_template_filename = PathBuf::new()
-        .join(relayer_state.config.path.email_templates)
-        .join(template_name);
-
-    // Read the email template file
-    let email_template = read_to_string(&email_template_filename).await?;
-
-    // Create a new Handlebars instance
-    let reg = Handlebars::new();
-
-    // Render the template with the provided data
-    let template = reg.render_template(&email_template, &render_data)?;
-    Ok(template)
+    relayer_state
+        .templates
+        .render(template_base, locale.or(Some(DEFAULT_LOCALE)), &render_data)
 }
 
-/// Sends an email using the configured SMTP server.
+/// Queues an email for delivery on the outbound send queue.
+///
+/// The email (and its optional `ExpectsReply`) is persisted before this
+/// function returns, so it survives a relayer restart; `run_outbound_worker`
+/// is responsible for the actual delivery, its retries, and -- only once
+/// delivery is confirmed -- registering the expected reply.
 ///
 /// # Arguments
 ///
@@ -283,40 +341,10 @@ async fn send_email(
     expects_reply: Option<ExpectsReply>,
     relayer_state: RelayerState,
 ) -> Result<()> {
-    // Send POST request to email server
-    let response = relayer_state
-        .http_client
-        .post(format!("{}/api/sendEmail", relayer_state.config.smtp_url))
-        .json(&email)
-        .send()
-        .await?;
-
-    // Check if the email was sent successfully
-    if !response.status().is_success() {
-        return Err(anyhow::anyhow!(
-            "Failed to send email: {}",
-            response.text().await.unwrap_or_default()
-        ));
-    }
-
-    // Handle expected reply if necessary
-    if let Some(expects_reply) = expects_reply {
-        let response_body: EmailResponse = response.json().await?;
-
-        let message_id = response_body.message_id;
-        insert_expected_reply(&relayer_state.db, &message_id, expects_reply.request_id).await?;
-    }
-
+    queue::enqueue_email(&relayer_state.db, &email, expects_reply.as_ref()).await?;
     Ok(())
 }
 
-/// Represents the response from the email server after sending an email.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct EmailResponse {
-    status: String,
-    message_id: String,
-}
-
 /// Represents an expectation of a reply to an email.
 pub struct ExpectsReply {
     request_id: Option<String>,
@@ -398,6 +426,7 @@ pub async fn handle_email(
         request_id: request.id,
         original_subject: parsed_email.get_subject_all()?,
         original_message_id: parsed_email.get_message_id().ok(),
+        locale: None,
     })
 }
 
@@ -0,0 +1,324 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::Utc;
+use sqlx::PgPool;
+use tokio::time::sleep;
+use uuid::Uuid;
+
+use crate::{
+    model::{insert_expected_reply, update_request, RequestStatus},
+    RelayerState,
+};
+
+use super::{transport::EmailResponse, EmailMessage, ExpectsReply};
+
+/// How many times a queued email is retried before it is given up on and
+/// marked permanently failed.
+const MAX_ATTEMPTS: i32 = 8;
+
+/// The base delay for exponential backoff between delivery attempts; the
+/// delay doubles on every attempt and is capped at one hour.
+const BASE_RETRY_DELAY_SECS: i64 = 15;
+const MAX_RETRY_DELAY_SECS: i64 = 60 * 60;
+
+/// How often the worker polls the queue for entries whose `next_attempt_at`
+/// has elapsed.
+const WORKER_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long a claim is honored before `claim_due_entries` treats it as
+/// abandoned and reclaims the row. Must comfortably exceed how long a single
+/// `transport.send` can take (`SmtpConfig::timeout_secs`, or the HTTP
+/// relay's own timeout) -- this is what lets a relayer crash mid-send
+/// recover the row instead of losing it in 'claimed' forever.
+const CLAIM_LEASE_SECS: i64 = 300;
+
+/// A row in the `outbound_email_queue` table: an `EmailMessage` persisted
+/// alongside its optional `ExpectsReply` before the first delivery attempt,
+/// so a relayer restart can pick up where it left off.
+///
+/// `expects_reply` records whether `send_email` was given `Some(ExpectsReply)`
+/// at all -- it is NOT the same thing as `expects_reply_request_id` being
+/// present, since `ExpectsReply` can itself carry no request id. Collapsing
+/// the two lost the "was a reply even expected" bit, so both are persisted.
+struct OutboundQueueEntry {
+    id: Uuid,
+    email: EmailMessage,
+    expects_reply: bool,
+    expects_reply_request_id: Option<String>,
+    attempts: i32,
+}
+
+/// Persists `email` (and its optional `expects_reply`) to the outbound
+/// queue so a background worker can deliver it with retries, surviving a
+/// relayer restart between the enqueue and the actual send.
+pub async fn enqueue_email(
+    pool: &PgPool,
+    email: &EmailMessage,
+    expects_reply: Option<&ExpectsReply>,
+) -> Result<Uuid> {
+    let id = Uuid::new_v4();
+    let email_json = serde_json::to_value(email)?;
+    let expects_reply_given = expects_reply.is_some();
+    let expects_reply_request_id = expects_reply.and_then(|e| e.request_id.clone());
+
+    sqlx::query(
+        "INSERT INTO outbound_email_queue \
+         (id, email_json, expects_reply, expects_reply_request_id, status, attempts, next_attempt_at) \
+         VALUES ($1, $2, $3, $4, 'pending', 0, now())",
+    )
+    .bind(id)
+    .bind(email_json)
+    .bind(expects_reply_given)
+    .bind(expects_reply_request_id)
+    .execute(pool)
+    .await?;
+
+    Ok(id)
+}
+
+/// Runs forever, polling for due queue entries and attempting delivery with
+/// capped exponential backoff on transient failure.
+pub async fn run_outbound_worker(relayer_state: RelayerState) -> Result<()> {
+    loop {
+        match claim_due_entries(&relayer_state.db, 20).await {
+            Ok(entries) => {
+                for entry in entries {
+                    process_entry(entry, &relayer_state).await;
+                }
+            }
+            Err(err) => tracing::error!("failed to claim outbound queue entries: {err:#}"),
+        }
+
+        sleep(WORKER_POLL_INTERVAL).await;
+    }
+}
+
+/// Attempts delivery of one queued entry. Only once delivery is confirmed
+/// does this run `insert_expected_reply` (and, for the initial Command
+/// email, advance the request to `EmailSent`) -- both were previously run
+/// the instant the email was queued, before it was ever actually sent.
+async fn process_entry(entry: OutboundQueueEntry, relayer_state: &RelayerState) {
+    let result = relayer_state
+        .transport
+        .send(&entry.email, relayer_state)
+        .await;
+
+    match result {
+        Ok(EmailResponse { message_id, .. }) => {
+            // Only the initial Command email is sent with `Some(ExpectsReply)`;
+            // Completion/Ack/Error emails are queued with `None` and must not
+            // gain an expected-reply row.
+            if entry.expects_reply {
+                if let Err(err) = insert_expected_reply(
+                    &relayer_state.db,
+                    &message_id,
+                    entry.expects_reply_request_id.clone(),
+                )
+                .await
+                {
+                    tracing::error!("failed to record expected reply for {}: {err:#}", entry.id);
+                }
+
+                if let Some(request_id) = entry
+                    .expects_reply_request_id
+                    .as_deref()
+                    .and_then(|id| id.parse::<Uuid>().ok())
+                {
+                    if let Err(err) =
+                        update_request(&relayer_state.db, request_id, RequestStatus::EmailSent)
+                            .await
+                    {
+                        tracing::error!(
+                            "failed to mark request {request_id} EmailSent after delivery: {err:#}"
+                        );
+                    }
+                }
+            }
+
+            if let Err(err) = mark_delivered(&relayer_state.db, entry.id).await {
+                tracing::error!(
+                    "failed to mark outbound email {} delivered: {err:#}",
+                    entry.id
+                );
+            }
+        }
+        Err(err) => {
+            let attempts = entry.attempts + 1;
+            let permanent = attempts >= MAX_ATTEMPTS || is_permanent_failure(&err);
+
+            if let Err(update_err) = record_attempt_failure(
+                &relayer_state.db,
+                entry.id,
+                attempts,
+                &err.to_string(),
+                permanent,
+            )
+            .await
+            {
+                tracing::error!(
+                    "failed to record delivery failure for {}: {update_err:#}",
+                    entry.id
+                );
+            }
+        }
+    }
+}
+
+/// Atomically claims up to `limit` entries: pending ones whose
+/// `next_attempt_at` has elapsed, plus already-`claimed` ones whose lease
+/// (`claimed_at`) expired more than `CLAIM_LEASE_SECS` ago, meaning whatever
+/// worker claimed them never reached `mark_delivered`/`record_attempt_failure`
+/// -- almost always because the relayer was killed mid-`transport.send`.
+/// Without the second clause a claim survives the worker that made it: the
+/// row's `status` would stay `'claimed'` forever and the queue's own
+/// durability guarantee would not hold across a restart during send.
+///
+/// The inner `SELECT ... FOR UPDATE SKIP LOCKED` and the `status`/`claimed_at`
+/// update run as a single statement, so the row locks are held until the
+/// claim itself is committed. A bare `SELECT ... FOR UPDATE SKIP LOCKED` run
+/// on its own is a single-statement transaction that releases its locks the
+/// instant the `SELECT` completes, which would let two workers polling at
+/// the same moment both claim and send the same row.
+async fn claim_due_entries(pool: &PgPool, limit: i64) -> Result<Vec<OutboundQueueEntry>> {
+    let rows: Vec<(Uuid, serde_json::Value, bool, Option<String>, i32)> = sqlx::query_as(
+        "UPDATE outbound_email_queue \
+         SET status = 'claimed', claimed_at = now() \
+         WHERE id IN ( \
+             SELECT id FROM outbound_email_queue \
+             WHERE (status = 'pending' AND next_attempt_at <= now()) \
+                OR (status = 'claimed' AND claimed_at <= now() - make_interval(secs => $2)) \
+             ORDER BY next_attempt_at \
+             LIMIT $1 \
+             FOR UPDATE SKIP LOCKED \
+         ) \
+         RETURNING id, email_json, expects_reply, expects_reply_request_id, attempts",
+    )
+    .bind(limit)
+    .bind(CLAIM_LEASE_SECS as f64)
+    .fetch_all(pool)
+    .await?;
+
+    rows.into_iter()
+        .map(
+            |(id, email_json, expects_reply, expects_reply_request_id, attempts)| {
+                Ok(OutboundQueueEntry {
+                    id,
+                    email: serde_json::from_value(email_json)?,
+                    expects_reply,
+                    expects_reply_request_id,
+                    attempts,
+                })
+            },
+        )
+        .collect()
+}
+
+/// Marks a queue entry delivered so it is not picked up again.
+async fn mark_delivered(pool: &PgPool, id: Uuid) -> Result<()> {
+    sqlx::query("UPDATE outbound_email_queue SET status = 'delivered' WHERE id = $1")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Records a failed delivery attempt. Transient failures are rescheduled
+/// with exponential backoff; permanent failures (exhausted retries, or a
+/// delivery error recognized as unrecoverable) stop retrying altogether.
+async fn record_attempt_failure(
+    pool: &PgPool,
+    id: Uuid,
+    attempts: i32,
+    error: &str,
+    permanent: bool,
+) -> Result<()> {
+    if permanent {
+        sqlx::query(
+            "UPDATE outbound_email_queue \
+             SET status = 'failed', attempts = $2, last_error = $3 \
+             WHERE id = $1",
+        )
+        .bind(id)
+        .bind(attempts)
+        .bind(error)
+        .execute(pool)
+        .await?;
+    } else {
+        let next_attempt_at = Utc::now() + chrono::Duration::seconds(backoff_delay_secs(attempts));
+        sqlx::query(
+            "UPDATE outbound_email_queue \
+             SET status = 'pending', attempts = $2, last_error = $3, next_attempt_at = $4 \
+             WHERE id = $1",
+        )
+        .bind(id)
+        .bind(attempts)
+        .bind(error)
+        .bind(next_attempt_at)
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// `2^attempts * BASE_RETRY_DELAY_SECS`, capped at `MAX_RETRY_DELAY_SECS`.
+fn backoff_delay_secs(attempts: i32) -> i64 {
+    let factor = 1i64
+        .checked_shl(attempts.clamp(0, 16) as u32)
+        .unwrap_or(i64::MAX);
+    BASE_RETRY_DELAY_SECS
+        .saturating_mul(factor)
+        .min(MAX_RETRY_DELAY_SECS)
+}
+
+/// A crude classifier for delivery errors that are never going to succeed
+/// on retry: an SMTP permanent (5xx) rejection, or an invalid recipient.
+/// Everything else -- timeouts, 4xx, connection resets -- is treated as
+/// transient and retried.
+fn is_permanent_failure(err: &anyhow::Error) -> bool {
+    let message = err.to_string().to_lowercase();
+    message.contains("550")
+        || message.contains("551")
+        || message.contains("553")
+        || message.contains("invalid recipient")
+        || message.contains("mailbox unavailable")
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use super::{
+        backoff_delay_secs, is_permanent_failure, BASE_RETRY_DELAY_SECS, MAX_RETRY_DELAY_SECS,
+    };
+
+    #[test]
+    fn backoff_doubles_with_each_attempt() {
+        assert_eq!(backoff_delay_secs(0), BASE_RETRY_DELAY_SECS);
+        assert_eq!(backoff_delay_secs(1), BASE_RETRY_DELAY_SECS * 2);
+        assert_eq!(backoff_delay_secs(2), BASE_RETRY_DELAY_SECS * 4);
+    }
+
+    #[test]
+    fn backoff_is_capped_at_the_maximum_delay() {
+        assert_eq!(backoff_delay_secs(10), MAX_RETRY_DELAY_SECS);
+        assert_eq!(backoff_delay_secs(63), MAX_RETRY_DELAY_SECS);
+    }
+
+    #[test]
+    fn smtp_5xx_rejections_are_permanent() {
+        assert!(is_permanent_failure(&anyhow!(
+            "550 5.1.1 No such user here"
+        )));
+        assert!(is_permanent_failure(&anyhow!("mailbox unavailable")));
+    }
+
+    #[test]
+    fn timeouts_and_resets_are_transient() {
+        assert!(!is_permanent_failure(&anyhow!("connection reset by peer")));
+        assert!(!is_permanent_failure(&anyhow!(
+            "421 4.3.2 service temporarily unavailable"
+        )));
+    }
+}
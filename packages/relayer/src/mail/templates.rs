@@ -0,0 +1,144 @@
+use std::{fs, path::Path};
+
+use anyhow::{Context, Result};
+use handlebars::{handlebars_helper, Handlebars};
+use serde_json::Value;
+
+/// The file extension every template and partial is expected to use.
+const TEMPLATE_EXTENSION: &str = "html";
+
+/// A long-lived registry of the relayer's Handlebars templates.
+///
+/// Every file under `email_templates/` is registered once at startup under
+/// its file stem (e.g. `command_template.html` becomes `command_template`,
+/// and `command_template.ja.html` becomes `command_template.ja`), and every
+/// file under `email_templates/partials/` is registered as a partial under
+/// its own file stem. This replaces re-reading the template file and
+/// constructing a fresh `Handlebars` instance on every send.
+pub struct TemplateRegistry {
+    handlebars: Handlebars<'static>,
+}
+
+impl TemplateRegistry {
+    /// Walks `email_templates_dir`, registering every top-level template and
+    /// every partial under `partials/`, and installs the relayer's custom
+    /// helpers.
+    pub fn load(email_templates_dir: &Path) -> Result<Self> {
+        let mut handlebars = Handlebars::new();
+        handlebars.set_strict_mode(true);
+        register_helpers(&mut handlebars);
+
+        register_templates_in_dir(&mut handlebars, email_templates_dir, false)?;
+
+        let partials_dir = email_templates_dir.join("partials");
+        if partials_dir.is_dir() {
+            register_templates_in_dir(&mut handlebars, &partials_dir, true)?;
+        }
+
+        Ok(Self { handlebars })
+    }
+
+    /// Renders `template_base` (e.g. `"command_template"`) for `locale`,
+    /// falling back to the default (non-localized) template when no
+    /// locale-specific variant is registered.
+    pub fn render(
+        &self,
+        template_base: &str,
+        locale: Option<&str>,
+        render_data: &Value,
+    ) -> Result<String> {
+        for candidate in template_candidates(template_base, locale) {
+            if self.handlebars.has_template(&candidate) {
+                return Ok(self.handlebars.render(&candidate, render_data)?);
+            }
+        }
+
+        Err(anyhow::anyhow!(
+            "No template registered for `{}` (locale: {:?})",
+            template_base,
+            locale
+        ))
+    }
+}
+
+/// Returns the names to look up, in priority order: the localized variant
+/// first, then the default template.
+fn template_candidates(template_base: &str, locale: Option<&str>) -> Vec<String> {
+    match locale {
+        Some(locale) => vec![
+            format!("{template_base}.{locale}"),
+            template_base.to_string(),
+        ],
+        None => vec![template_base.to_string()],
+    }
+}
+
+/// Registers every `*.html` file directly inside `dir` under its file stem
+/// (stripping the single `.html` extension, so localized variants keep their
+/// locale suffix, e.g. `command_template.ja.html` -> `command_template.ja`).
+fn register_templates_in_dir(
+    handlebars: &mut Handlebars<'static>,
+    dir: &Path,
+    as_partials: bool,
+) -> Result<()> {
+    for entry in fs::read_dir(dir).with_context(|| format!("reading template dir {dir:?}"))? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some(TEMPLATE_EXTENSION) {
+            continue;
+        }
+
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .with_context(|| format!("template file has no valid name: {path:?}"))?
+            .to_string();
+
+        let contents =
+            fs::read_to_string(&path).with_context(|| format!("reading template file {path:?}"))?;
+
+        if as_partials {
+            handlebars.register_partial(&name, contents)?;
+        } else {
+            handlebars.register_template_string(&name, contents)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Installs the relayer's custom Handlebars helpers, shared across every
+/// rendered template.
+fn register_helpers(handlebars: &mut Handlebars<'static>) {
+    handlebars_helper!(truncate: |s: str, len: usize| {
+        if s.chars().count() > len {
+            s.chars().take(len).collect::<String>() + "…"
+        } else {
+            s.to_string()
+        }
+    });
+    handlebars.register_helper("truncate", Box::new(truncate));
+
+    handlebars_helper!(uppercase: |s: str| s.to_uppercase());
+    handlebars.register_helper("uppercase", Box::new(uppercase));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::template_candidates;
+
+    #[test]
+    fn no_locale_only_tries_the_default_template() {
+        assert_eq!(
+            template_candidates("command_template", None),
+            vec!["command_template"]
+        );
+    }
+
+    #[test]
+    fn locale_is_tried_before_falling_back_to_the_default_template() {
+        assert_eq!(
+            template_candidates("command_template", Some("ja")),
+            vec!["command_template.ja", "command_template"]
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use lettre::{
+    transport::smtp::{
+        authentication::{Credentials, Mechanism},
+        client::{Tls, TlsParameters},
+    },
+    AsyncSmtpTransport, AsyncTransport, Tokio1Executor,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::RelayerState;
+
+use super::{mime::build_message, EmailMessage};
+
+/// Which flavor of transport-layer security to negotiate with the SMTP server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpTlsMode {
+    /// Wrap the connection in TLS from the first byte, e.g. on port 465.
+    Wrapper,
+    /// Issue `STARTTLS` after connecting, falling back to plaintext if the
+    /// server does not advertise support for it.
+    OpportunisticStartTls,
+    /// Issue `STARTTLS` after connecting and fail the connection if the
+    /// server does not upgrade.
+    RequiredStartTls,
+}
+
+/// Which SASL mechanism to authenticate with, once credentials are configured.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpAuthMechanism {
+    Plain,
+    Login,
+}
+
+/// Configuration for the native SMTP transport. Deployments that still rely
+/// on the external HTTP relay simply omit this from their config.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub tls: SmtpTlsMode,
+    /// The mailbox every outgoing message is sent `From`, e.g.
+    /// `"ZK Email <noreply@prove.email>"`. `EmailMessage` carries no sender
+    /// address of its own -- its `reply_to`/`reference` fields hold the
+    /// original message's Message-ID for threading, not a mailbox -- so this
+    /// is the only source of a `From` address the native SMTP path has.
+    pub from: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    pub auth_mechanism: Option<SmtpAuthMechanism>,
+    #[serde(default = "default_smtp_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_smtp_timeout_secs() -> u64 {
+    30
+}
+
+/// The response produced by delivering an `EmailMessage`, regardless of which
+/// backend handled it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailResponse {
+    pub status: String,
+    pub message_id: String,
+}
+
+/// An email delivery backend.
+///
+/// `Http` preserves the original behavior of POSTing the `EmailMessage` as
+/// JSON to an external relay. `Smtp` delivers directly via `lettre`, reusing
+/// a single pooled connection across sends instead of opening one per email.
+#[derive(Clone)]
+pub enum Transport {
+    Http,
+    Smtp(Box<AsyncSmtpTransport<Tokio1Executor>>, String),
+}
+
+impl Transport {
+    /// Builds a `Transport` from the relayer's SMTP configuration. Returns
+    /// `Transport::Http` when no SMTP configuration is present, preserving
+    /// the existing relay-based behavior.
+    pub fn from_config(smtp_config: Option<&SmtpConfig>) -> Result<Self> {
+        let Some(cfg) = smtp_config else {
+            return Ok(Transport::Http);
+        };
+
+        let mut builder = match cfg.tls {
+            SmtpTlsMode::Wrapper => AsyncSmtpTransport::<Tokio1Executor>::relay(&cfg.host)?
+                .tls(Tls::Wrapper(TlsParameters::new(cfg.host.clone())?)),
+            SmtpTlsMode::OpportunisticStartTls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&cfg.host)?
+                    .tls(Tls::Opportunistic(TlsParameters::new(cfg.host.clone())?))
+            }
+            SmtpTlsMode::RequiredStartTls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&cfg.host)?
+                    .tls(Tls::Required(TlsParameters::new(cfg.host.clone())?))
+            }
+        }
+        .port(cfg.port)
+        .timeout(Some(Duration::from_secs(cfg.timeout_secs)));
+
+        if let (Some(username), Some(password)) = (&cfg.username, &cfg.password) {
+            let mechanism = match cfg.auth_mechanism {
+                Some(SmtpAuthMechanism::Login) => Mechanism::Login,
+                _ => Mechanism::Plain,
+            };
+            builder = builder
+                .credentials(Credentials::new(username.clone(), password.clone()))
+                .authentication(vec![mechanism]);
+        }
+
+        Ok(Transport::Smtp(Box::new(builder.build()), cfg.from.clone()))
+    }
+
+    /// Delivers `email` via the selected backend and returns the generated
+    /// Message-ID so `send_email` can keep registering it against the
+    /// expected-reply table.
+    pub async fn send(
+        &self,
+        email: &EmailMessage,
+        relayer_state: &RelayerState,
+    ) -> Result<EmailResponse> {
+        match self {
+            Transport::Http => send_via_http(email, relayer_state).await,
+            Transport::Smtp(transport, from) => send_via_smtp(transport, from, email).await,
+        }
+    }
+}
+
+async fn send_via_http(
+    email: &EmailMessage,
+    relayer_state: &RelayerState,
+) -> Result<EmailResponse> {
+    let response = relayer_state
+        .http_client
+        .post(format!("{}/api/sendEmail", relayer_state.config.smtp_url))
+        .json(email)
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!(
+            "Failed to send email: {}",
+            response.text().await.unwrap_or_default()
+        ));
+    }
+
+    Ok(response.json().await?)
+}
+
+async fn send_via_smtp(
+    transport: &AsyncSmtpTransport<Tokio1Executor>,
+    from: &str,
+    email: &EmailMessage,
+) -> Result<EmailResponse> {
+    let (message, message_id) = build_message(email, from)?;
+
+    transport.send(message).await?;
+
+    Ok(EmailResponse {
+        status: "sent".to_string(),
+        message_id,
+    })
+}